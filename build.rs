@@ -0,0 +1,69 @@
+extern crate phf_codegen;
+
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Parse `usb.ids` into two phf maps the way the usb-ids crate's build script
+// does: `VENDORS` keyed by vendor id, and `PRODUCTS` keyed by a packed
+// `(vendor << 16) | product` u32. The generated file is `include!`d by
+// src/usb_ids.rs.
+fn main() {
+    println!("cargo:rerun-if-changed=usb.ids");
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dst = Path::new(&out_dir).join("usb_ids_generated.rs");
+    let mut out = BufWriter::new(File::create(&dst).unwrap());
+
+    let mut vendors = phf_codegen::Map::new();
+    let mut products = phf_codegen::Map::new();
+    let mut current_vendor: Option<u16> = None;
+
+    let ids = BufReader::new(File::open("usb.ids").unwrap());
+    for line in ids.lines() {
+        let line = line.unwrap();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('\t') {
+            // Product line: `\t<pid>  <name>`.
+            if let Some(vendor) = current_vendor {
+                let line = &line[1..];
+                if let Some((pid, name)) = split_entry(line) {
+                    let key = ((vendor as u32) << 16) | (pid as u32);
+                    products.entry(key, &quote(name));
+                }
+            }
+        } else {
+            // Vendor line: `<vid>  <name>`.
+            if let Some((vid, name)) = split_entry(&line) {
+                current_vendor = Some(vid);
+                vendors.entry(vid, &quote(name));
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "static VENDORS: phf::Map<u16, &'static str> = {};",
+        vendors.build()
+    ).unwrap();
+    writeln!(
+        out,
+        "static PRODUCTS: phf::Map<u32, &'static str> = {};",
+        products.build()
+    ).unwrap();
+}
+
+// Split an id/name entry into its hex id and trimmed name (two spaces apart).
+fn split_entry(line: &str) -> Option<(u16, &str)> {
+    let mut parts = line.splitn(2, "  ");
+    let id = parts.next()?.trim();
+    let name = parts.next()?.trim();
+    u16::from_str_radix(id, 16).ok().map(|id| (id, name))
+}
+
+fn quote(s: &str) -> String {
+    format!("{:?}", s)
+}
@@ -1,6 +1,9 @@
 use sha1;
-use ioreg;
+use toml;
+use enumerate;
+use usb_ids;
 use clap::ArgMatches;
+use std::env;
 use std::path::PathBuf;
 use std::fs;
 use std::io::Read;
@@ -12,8 +15,14 @@ pub struct UsbDevice {
     pub product_id: u16,
     pub vendor_string: String,
     pub product_string: String,
-    pub serial_number: String,    
+    pub serial_number: String,
     pub location_id: Option<i64>,
+    /// Originating device node in the platform's device tree, captured at
+    /// enumeration time. The Linux backend stores the `/sys/bus/usb/devices`
+    /// directory here so later operations address this exact instance instead
+    /// of re-matching by `(vid, pid, serial)`, which is ambiguous for two
+    /// identical probes. `None` on backends that do not carry a handle.
+    pub sysfs_path: Option<PathBuf>,
 }
 
 impl UsbDevice {
@@ -24,6 +33,37 @@ impl UsbDevice {
         h.update(self.serial_number.as_bytes());
         h.digest().to_string()
     }
+
+    /// Vendor name for display, preferring the device's own descriptor
+    /// string and falling back to the `usb.ids` database when it is blank
+    /// (common on Linux, where string descriptors are not always read).
+    pub fn vendor_name(&self) -> String {
+        if !self.vendor_string.is_empty() {
+            return self.vendor_string.clone()
+        }
+        usb_ids::vendor(self.vendor_id).unwrap_or("").to_owned()
+    }
+
+    /// Product name for display, falling back to `usb.ids` as `vendor_name`
+    /// does.
+    pub fn product_name(&self) -> String {
+        if !self.product_string.is_empty() {
+            return self.product_string.clone()
+        }
+        usb_ids::product(self.vendor_id, self.product_id).unwrap_or("").to_owned()
+    }
+
+    /// Stable physical-port identifier for the device, used by the
+    /// `--usb-path` filter to pin a board to a bus-topology location
+    /// regardless of which identical probe is plugged in. On Linux this is
+    /// the sysfs bus/port chain (e.g. `2-1.4`); on macOS it is the hex
+    /// `location_id`.
+    pub fn usb_path(&self) -> String {
+        #[cfg(target_os = "linux")]
+        return enumerate::usb_path(self);
+        #[cfg(not(target_os = "linux"))]
+        return format!("{:x}", self.location_id.unwrap_or(0));
+    }
 }
 
 pub trait Device {
@@ -36,6 +76,16 @@ pub trait Device {
     fn serial_path(&self) -> Option<String> { None }
     fn msd_path(&self) -> Option<PathBuf> { None }
     fn openocd_serial(&self) -> Option<String> { None }
+
+    /// Force a USB re-enumeration of the probe to recover it from a wedged
+    /// state. Implemented on Linux via the enumeration backend; unsupported
+    /// elsewhere.
+    fn reset_usb(&self) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        return enumerate::reset_usb(self.usb());
+        #[cfg(not(target_os = "linux"))]
+        return Err("usb reset is not supported on this platform".into());
+    }
 }
 
 pub struct UnknownDevice {
@@ -54,131 +104,173 @@ impl Device for UnknownDevice {
     }
 }
 
-pub struct JLinkDevice {
-    usb: UsbDevice,
+/// A single probe description from the registry. `serial_path` and
+/// `openocd_serial` are template strings rendered against the enumerated
+/// `UsbDevice`; the supported interpolation tokens are `{location_id:x}`,
+/// `{serial}` and `{serial[..7]}`, with any other characters (including a
+/// literal interface index) passed through verbatim. `{location_id:x}`
+/// reproduces the historical macOS `cu.usbmodem` naming, which renders the
+/// location id as hex with every `0` digit stripped (e.g. `0x14200000` ->
+/// `142`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbeSpec {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub device_type: String,
+    #[serde(default)]
+    pub openocd_serial: Option<String>,
+    #[serde(default)]
+    pub serial_path: Option<String>,
+    #[serde(default)]
+    pub msd_volume: Option<String>,
+    /// USB interface number whose tty the serial port is exposed on. Probes
+    /// such as the ST-Link V2.1 present more than one CDC-ACM interface, so the
+    /// backend must follow this specific interface rather than whichever tty
+    /// sysfs happens to list first.
+    #[serde(default)]
+    pub interface: Option<u8>,
 }
 
-impl Device for JLinkDevice {
-    fn usb(&self) -> &UsbDevice {
-        &self.usb
-    }
-
-    fn device_type(&self) -> Option<&str> {
-        Some("JLink")
-    }
-
-    fn serial_path(&self) -> Option<String> {
-        Some(format!("/dev/cu.usbmodem{}{}", 
-            format!("{:x}", self.usb.location_id.unwrap_or(0)).replace("0",""),
-            1,
-        ))
-    }
-
-    fn openocd_serial(&self) -> Option<String> {
-        Some(format!("jlink_serial {}", self.usb.serial_number))
+impl ProbeSpec {
+    // Expand the interpolation tokens in `template` against `usb`.
+    fn render(&self, template: &str, usb: &UsbDevice) -> String {
+        let serial7 = if usb.serial_number.len() >= 7 {
+            &usb.serial_number[..7]
+        } else {
+            &usb.serial_number[..]
+        };
+        // The macOS `cu.usbmodem` names are built from the location id hex
+        // with every `0` digit stripped, so reproduce that here byte-for-byte.
+        let location = format!("{:x}", usb.location_id.unwrap_or(0)).replace("0", "");
+        template
+            .replace("{location_id:x}", &location)
+            .replace("{serial[..7]}", serial7)
+            .replace("{serial}", &usb.serial_number)
     }
 }
 
-pub struct StLinkV2Device {
-    usb: UsbDevice,
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    probe: Vec<ProbeSpec>,
 }
 
-impl Device for StLinkV2Device {
-    fn usb(&self) -> &UsbDevice {
-        &self.usb
-    }
-
-    fn device_type(&self) -> Option<&str> {
-        Some("STLinkV2")
-    }
-
-    fn openocd_serial(&self) -> Option<String> {
-        Some(format!("hla_serial {}", self.usb.serial_number))
-    }    
+/// The resolved set of probe descriptions. Entries loaded from the user's
+/// `~/.config/bobbin/probes.toml` take precedence over the embedded defaults,
+/// so new hardware can be supported without recompiling.
+pub struct Registry {
+    probes: Vec<ProbeSpec>,
 }
 
-pub struct StLinkV21Device {
-    usb: UsbDevice,
-}
+const DEFAULT_PROBES: &'static str = include_str!("probes.toml");
 
-impl Device for StLinkV21Device {
-    fn usb(&self) -> &UsbDevice {
-        &self.usb
+impl Registry {
+    pub fn load() -> Registry {
+        let mut probes = Vec::new();
+        if let Some(user) = user_probes_path() {
+            if let Some(file) = read_registry(&user) {
+                probes.extend(file.probe);
+            }
+        }
+        if let Ok(file) = toml::from_str::<RegistryFile>(DEFAULT_PROBES) {
+            probes.extend(file.probe);
+        }
+        Registry { probes: probes }
     }
 
-    fn device_type(&self) -> Option<&str> {
-        Some("STLinkV21")
+    fn spec(&self, vendor_id: u16, product_id: u16) -> Option<ProbeSpec> {
+        self.probes
+            .iter()
+            .find(|p| p.vendor_id == vendor_id && p.product_id == product_id)
+            .cloned()
     }
 
-    fn serial_path(&self) -> Option<String> {
-        Some(format!("/dev/cu.usbmodem{}{}", 
-            format!("{:x}", self.usb.location_id.unwrap_or(0)).replace("0",""),
-            3,
-        ))
-    }    
-
-    fn openocd_serial(&self) -> Option<String> {
-        Some(format!("hla_serial {}", self.usb.serial_number))
-    }        
+    /// Resolve `usb` to a concrete `Device`, matching the highest-priority
+    /// registry entry for its VID/PID or `UnknownDevice` if none matches.
+    pub fn device_for(&self, usb: UsbDevice) -> Box<Device> {
+        match self.spec(usb.vendor_id, usb.product_id) {
+            Some(spec) => Box::new(ConfiguredDevice { usb: usb, spec: spec }),
+            None => Box::new(UnknownDevice { usb: usb }),
+        }
+    }
 }
 
-pub struct TiIcdiDevice {
-    usb: UsbDevice,
+fn user_probes_path() -> Option<PathBuf> {
+    env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".config/bobbin/probes.toml"))
 }
 
-impl Device for TiIcdiDevice {
-    fn usb(&self) -> &UsbDevice {
-        &self.usb
-    }
-
-    fn device_type(&self) -> Option<&str> {
-        Some("TI-ICDI")
+fn read_registry(path: &PathBuf) -> Option<RegistryFile> {
+    let mut f = match fs::File::open(path) {
+        Ok(f) => f,
+        Err(_) => return None,
+    };
+    let mut s = String::new();
+    if f.read_to_string(&mut s).is_err() {
+        return None;
     }
-
-    fn serial_path(&self) -> Option<String> {
-        Some(format!("/dev/cu.usbmodem{}{}", &self.usb.serial_number[..7], 1))
-    }    
-
-    fn openocd_serial(&self) -> Option<String> {
-        Some(format!("hla_serial {}", self.usb.serial_number))
-    }        
+    toml::from_str(&s).ok()
 }
 
-pub struct DapLinkDevice {
+/// A device whose behaviour is driven entirely by a `ProbeSpec` from the
+/// registry, replacing the per-probe `Device` structs that used to bake the
+/// templates into the binary.
+pub struct ConfiguredDevice {
     usb: UsbDevice,
+    spec: ProbeSpec,
 }
 
-impl Device for DapLinkDevice {
+impl Device for ConfiguredDevice {
     fn usb(&self) -> &UsbDevice {
         &self.usb
     }
 
     fn device_type(&self) -> Option<&str> {
-        Some("DAPLink")
+        Some(&self.spec.device_type)
     }
 
     fn serial_path(&self) -> Option<String> {
-        Some(format!("/dev/cu.usbmodem{}{}", 
-            format!("{:x}", self.usb.location_id.unwrap_or(0)).replace("0",""),
-            2,
-        ))
+        #[cfg(target_os = "linux")]
+        return enumerate::serial_path(&self.usb, self.spec.interface);
+        #[cfg(not(target_os = "linux"))]
+        return self.spec.serial_path.as_ref().map(|t| self.spec.render(t, &self.usb));
     }
 
+    #[cfg(target_os = "linux")]
     fn msd_path(&self) -> Option<PathBuf> {
-        // Look in /Volumes/DAPLINK*/ for DETAILS.TXT
-        // Look for Unique ID line == serial number
+        if self.spec.msd_volume.is_none() {
+            return None;
+        }
+        enumerate::msd_path(&self.usb)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn msd_path(&self) -> Option<PathBuf> {
+        // Look in /Volumes/<msd_volume>*/ for DETAILS.TXT and pick the volume
+        // whose file contains the serial number anywhere in its text.
+        let prefix = match self.spec.msd_volume {
+            Some(ref prefix) => format!("/Volumes/{}", prefix),
+            None => return None,
+        };
         if let Ok(volumes) = fs::read_dir("/Volumes/") {
-            for volume in volumes {                
-                if let Ok(volume) = volume {                    
-                    //println!("checking {:?} {}", volume.path(), volume.path().to_string_lossy().starts_with("/Volumes/DAPLINK") );
-                    if volume.path().to_string_lossy().starts_with("/Volumes/DAPLINK") {                        
+            for volume in volumes {
+                if let Ok(volume) = volume {
+                    if volume.path().to_string_lossy().starts_with(&prefix) {
                         let details = volume.path().join("DETAILS.TXT");
-                        let mut f = fs::File::open(details).expect("Error opening DETAILS.TXT");
+                        // A mounted volume may have no readable DETAILS.TXT;
+                        // skip it rather than aborting enumeration.
+                        let mut f = match fs::File::open(details) {
+                            Ok(f) => f,
+                            Err(_) => continue,
+                        };
                         let mut s = String::new();
-                        f.read_to_string(&mut s).expect("Error reading details");
+                        if f.read_to_string(&mut s).is_err() {
+                            continue;
+                        }
                         if s.contains(&self.usb.serial_number) {
                             return Some(volume.path())
-                        }                        
+                        }
                     }
                 }
             }
@@ -187,58 +279,124 @@ impl Device for DapLinkDevice {
     }
 
     fn openocd_serial(&self) -> Option<String> {
-        Some(format!("cmsis_dap_serial {}", self.usb.serial_number))
-    }    
-    
+        self.spec.openocd_serial.as_ref().map(|t| self.spec.render(t, &self.usb))
+    }
 }
 
 pub struct DeviceFilter {
     all: bool,
     device: Option<String>,
+    path: Option<String>,
 }
 
 impl<'a> From<&'a ArgMatches<'a>> for DeviceFilter {
     fn from(other: &ArgMatches) -> DeviceFilter {
         DeviceFilter {
             all: other.is_present("all"),
-            device: other.value_of("device").map(String::from)
+            device: other.value_of("device").map(String::from),
+            path: other.value_of("usb-path").map(String::from),
         }
     }
 }
 
-pub fn lookup(usb: UsbDevice) -> Box<Device> {
-    match (usb.vendor_id, usb.product_id) {
-        (0x0d28, 0x0204) => Box::new(DapLinkDevice { usb: usb }),
-        (0x03eb, 0x2157) => Box::new(DapLinkDevice { usb: usb }),
-        (0x0483, 0x3748) => Box::new(StLinkV2Device { usb: usb }),
-        (0x0483, 0x374b) => Box::new(StLinkV21Device { usb: usb }),
-        (0x1366, 0x0101) => Box::new(JLinkDevice { usb: usb }),
-        (0x1366, 0x0105) => Box::new(JLinkDevice { usb: usb }),
-        (0x1cbe, 0x00fd) => Box::new(TiIcdiDevice { usb: usb }),
-        _ => Box::new(UnknownDevice { usb: usb })
+impl DeviceFilter {
+    /// Test whether `device` satisfies every active selector.
+    pub fn matches(&self, device: &Device) -> bool {
+        if !self.all && device.is_unknown() {
+            return false
+        }
+
+        if let Some(ref hash) = self.device {
+            if !device.hash().starts_with(hash) {
+                return false
+            }
+        }
+
+        if let Some(ref path) = self.path {
+            if &device.usb().usb_path() != path {
+                return false
+            }
+        }
+
+        true
     }
 }
 
+pub fn lookup(usb: UsbDevice) -> Box<Device> {
+    Registry::load().device_for(usb)
+}
+
 
 pub fn enumerate() -> Result<Vec<Box<Device>>> {
-    Ok(ioreg::enumerate()?.into_iter().map(lookup).collect())
+    let registry = Registry::load();
+    Ok(enumerate::enumerate()?.into_iter().map(|usb| registry.device_for(usb)).collect())
 }
 
 pub fn search(filter: &DeviceFilter) -> Result<Vec<Box<Device>>> {
-    Ok(enumerate()?.into_iter().filter(|d| {
-        if !filter.all {
-            if d.is_unknown() {
-                return false
-            }
+    Ok(enumerate()?.into_iter().filter(|d| filter.matches(d.as_ref())).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usb(location_id: Option<i64>, serial: &str) -> UsbDevice {
+        UsbDevice {
+            vendor_id: 0x0d28,
+            product_id: 0x0204,
+            vendor_string: String::new(),
+            product_string: String::new(),
+            serial_number: serial.to_owned(),
+            location_id: location_id,
+            sysfs_path: None,
         }
+    }
 
-        if let Some(ref device) = filter.device {
-            if !d.hash().starts_with(device) {
-                return false
-            }
+    fn spec(device_type: &str, serial_path: Option<&str>) -> ProbeSpec {
+        ProbeSpec {
+            vendor_id: 0x0d28,
+            product_id: 0x0204,
+            device_type: device_type.to_owned(),
+            openocd_serial: None,
+            serial_path: serial_path.map(String::from),
+            msd_volume: None,
+            interface: None,
         }
+    }
 
+    #[test]
+    fn render_strips_zeros_from_location_id() {
+        // 0x14200000 hex is "14200000"; stripping every '0' leaves "142", the
+        // byte-identical macOS cu.usbmodem stem.
+        let s = spec("DAPLink", None);
+        let rendered = s.render("/dev/cu.usbmodem{location_id:x}2", &usb(Some(0x14200000), ""));
+        assert_eq!(rendered, "/dev/cu.usbmodem1422");
+    }
 
-        true
-    }).collect())
+    #[test]
+    fn render_expands_serial_tokens() {
+        let s = spec("JLink", None);
+        let dev = usb(None, "0123456789");
+        assert_eq!(s.render("{serial}", &dev), "0123456789");
+        assert_eq!(s.render("{serial[..7]}", &dev), "0123456");
+    }
+
+    #[test]
+    fn render_short_serial_is_not_truncated() {
+        // Serials shorter than seven characters must pass through whole rather
+        // than panic on the slice.
+        let s = spec("TI-ICDI", None);
+        assert_eq!(s.render("{serial[..7]}", &usb(None, "ABC")), "ABC");
+    }
+
+    #[test]
+    fn registry_prefers_user_entry_over_default() {
+        // `load()` extends user entries before the defaults and `spec()` takes
+        // the first match, so a user override of the same VID/PID wins.
+        let registry = Registry {
+            probes: vec![spec("UserOverride", None), spec("Default", None)],
+        };
+        let resolved = registry.spec(0x0d28, 0x0204).unwrap();
+        assert_eq!(resolved.device_type, "UserOverride");
+    }
 }
\ No newline at end of file
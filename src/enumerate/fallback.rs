@@ -0,0 +1,10 @@
+use device::UsbDevice;
+use Result;
+
+/// Fallback backend for platforms without a native enumeration path (notably
+/// Windows, where an `ioreg`/sysfs equivalent is not yet wired up). It reports
+/// no devices so the crate still builds and runs; `serial_path`/`msd_path` are
+/// then rendered from the registry templates by the `Device` impls.
+pub fn enumerate() -> Result<Vec<UsbDevice>> {
+    Ok(Vec::new())
+}
@@ -0,0 +1,318 @@
+use device::UsbDevice;
+use libc;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use Result;
+
+// USBDEVFS_RESET = _IO('U', 20); re-enumerates the port without a physical
+// replug.
+const USBDEVFS_RESET: libc::c_ulong = 0x5514;
+
+const USB_DEVICES: &'static str = "/sys/bus/usb/devices";
+
+/// Linux backend. Walks `/sys/bus/usb/devices`, reading the descriptor
+/// attributes (`idVendor`, `idProduct`, `manufacturer`, `product`, `serial`)
+/// that the kernel exposes for every enumerated USB device. This mirrors the
+/// way udev profilers read `iManufacturer`/`iProduct`/`iSerial` without
+/// opening the device.
+pub fn enumerate() -> Result<Vec<UsbDevice>> {
+    let mut devices = Vec::new();
+    let entries = match fs::read_dir(USB_DEVICES) {
+        Ok(entries) => entries,
+        // No usb subsystem exposed (e.g. minimal container): nothing to report.
+        Err(_) => return Ok(devices),
+    };
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        // Interface nodes (`2-1:1.0`) carry no device descriptor; only
+        // whole-device nodes, root hubs (`usbN`) included, expose `idVendor`.
+        if !path.join("idVendor").exists() {
+            continue;
+        }
+        if let Some(dev) = read_device(&path) {
+            devices.push(dev);
+        }
+    }
+    Ok(devices)
+}
+
+fn read_device(path: &Path) -> Option<UsbDevice> {
+    match (read_hex(path, "idVendor"), read_hex(path, "idProduct")) {
+        (Some(vendor_id), Some(product_id)) => Some(UsbDevice {
+            vendor_id: vendor_id,
+            product_id: product_id,
+            vendor_string: read_string(path, "manufacturer"),
+            product_string: read_string(path, "product"),
+            serial_number: read_string(path, "serial"),
+            location_id: None,
+            sysfs_path: Some(path.to_path_buf()),
+        }),
+        _ => None,
+    }
+}
+
+/// Resolve the `/dev/ttyACM*` node backing `usb` by following the device's
+/// USB interface down to its tty child in sysfs, rather than synthesizing a
+/// name from the location id the way the macOS backend does. When the registry
+/// entry names an `interface`, only that interface is followed; probes such as
+/// the ST-Link V2.1 expose several CDC-ACM interfaces and the serial port lives
+/// on a specific one, so taking whichever tty sysfs lists first can hand back
+/// the wrong port.
+pub fn serial_path(usb: &UsbDevice, interface: Option<u8>) -> Option<String> {
+    let dir = match device_dir(usb) {
+        Some(dir) => dir,
+        None => return None,
+    };
+    // Interfaces are nested under the device node as `<dev>:<cfg>.<iface>`.
+    // Sort them so the scan order is stable across reads.
+    let mut ifaces: Vec<PathBuf> = match fs::read_dir(&dir) {
+        Ok(ifaces) => ifaces.filter_map(|i| i.ok().map(|i| i.path())).collect(),
+        Err(_) => return None,
+    };
+    ifaces.sort();
+    for iface in ifaces {
+        // Skip interfaces the registry entry did not name, when it named one.
+        if let Some(want) = interface {
+            if read_hex8(&iface, "bInterfaceNumber") != Some(want) {
+                continue;
+            }
+        }
+        // A CDC-ACM interface carries a `tty/ttyACMn` (newer kernels) or a
+        // bare `ttyACMn` child directory.
+        for parent in &[iface.join("tty"), iface.clone()] {
+            if let Ok(ttys) = fs::read_dir(parent) {
+                for tty in ttys {
+                    if let Ok(tty) = tty {
+                        let name = tty.file_name();
+                        let name = name.to_string_lossy();
+                        if name.starts_with("ttyACM") {
+                            return Some(format!("/dev/{}", name));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Locate the mass-storage mount for `usb` by matching its serial number
+/// against the `/dev/disk/by-id/usb-*` symlinks the kernel creates, then
+/// resolving the backing block device to its mountpoint via `/proc/mounts`.
+/// This returns a mounted directory (e.g. `/media/user/DAPLINK`) that callers
+/// can copy firmware into, not the raw block-device node, replacing the macOS
+/// `/Volumes/` scan.
+pub fn msd_path(usb: &UsbDevice) -> Option<PathBuf> {
+    if usb.serial_number.is_empty() {
+        return None;
+    }
+    let links = match fs::read_dir("/dev/disk/by-id") {
+        Ok(links) => links,
+        Err(_) => return None,
+    };
+    for link in links {
+        let link = match link {
+            Ok(link) => link.path(),
+            Err(_) => continue,
+        };
+        let name = link.file_name().map(|n| n.to_string_lossy().into_owned());
+        if let Some(name) = name {
+            if name.starts_with("usb-") && name.contains(&usb.serial_number) {
+                // The by-id entry is a symlink to the block node (e.g.
+                // `../../sdb`); resolve it and look up where it is mounted.
+                if let Ok(node) = fs::canonicalize(&link) {
+                    if let Some(mount) = mountpoint(&node) {
+                        return Some(mount);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+// Return the directory `node` (a canonical block-device path like `/dev/sdb1`)
+// is mounted at, by scanning `/proc/mounts`.
+fn mountpoint(node: &Path) -> Option<PathBuf> {
+    let mounts = match fs::File::open("/proc/mounts") {
+        Ok(f) => f,
+        Err(_) => return None,
+    };
+    let mut reader = BufReader::new(mounts);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+        let mut fields = line.split_whitespace();
+        let dev = match fields.next() {
+            Some(dev) => dev,
+            None => continue,
+        };
+        let mount = match fields.next() {
+            Some(mount) => mount,
+            None => continue,
+        };
+        // Only canonical `/dev/...` entries can match the resolved node.
+        if let Ok(dev) = fs::canonicalize(dev) {
+            if dev.as_path() == node {
+                // `/proc/mounts` escapes spaces and a few other characters as
+                // octal; decode them so the returned path is usable.
+                return Some(PathBuf::from(unescape_mount(mount)));
+            }
+        }
+    }
+}
+
+// Decode the octal escapes (`\040`, `\011`, `\012`, `\134`) that `/proc/mounts`
+// uses for spaces, tabs, newlines and backslashes in mount paths.
+fn unescape_mount(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut bytes = field.bytes().peekable();
+    while let Some(b) = bytes.next() {
+        if b == b'\\' {
+            let mut octal = String::new();
+            for _ in 0..3 {
+                match bytes.peek() {
+                    Some(&d) if (b'0'..=b'7').contains(&d) => {
+                        octal.push(d as char);
+                        bytes.next();
+                    }
+                    _ => break,
+                }
+            }
+            if let Ok(code) = u8::from_str_radix(&octal, 8) {
+                out.push(code as char);
+                continue;
+            }
+            out.push('\\');
+        } else {
+            out.push(b as char);
+        }
+    }
+    out
+}
+
+/// Force a USB port reset for `usb` to recover a wedged probe. Issues the
+/// `USBDEVFS_RESET` ioctl on the device's `/dev/bus/usb/BBB/DDD` node; if that
+/// fails (e.g. the node is missing), it falls back to an unbind/rebind cycle
+/// through the `usb` driver's sysfs files.
+pub fn reset_usb(usb: &UsbDevice) -> Result<()> {
+    let dir = match device_dir(usb) {
+        Some(dir) => dir,
+        None => return Err("device is no longer present".into()),
+    };
+
+    if let (Some(busnum), Some(devnum)) =
+        (read_dec(&dir, "busnum"), read_dec(&dir, "devnum"))
+    {
+        let node = format!("/dev/bus/usb/{:03}/{:03}", busnum, devnum);
+        // USBDEVFS_RESET needs a writable fd; a read-only open returns EPERM
+        // on standard kernels and would always drop to the rebind fallback.
+        if let Ok(f) = fs::OpenOptions::new().read(true).write(true).open(&node) {
+            let rc = unsafe { libc::ioctl(f.as_raw_fd(), USBDEVFS_RESET) };
+            if rc == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    rebind(&dir)
+}
+
+// Fallback reset: write the device's sysfs name to the usb driver's `unbind`
+// then `bind` attributes.
+fn rebind(dir: &Path) -> Result<()> {
+    let name = match dir.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Err("could not determine device name".into()),
+    };
+    write_driver("unbind", &name)?;
+    write_driver("bind", &name)
+}
+
+fn write_driver(action: &str, name: &str) -> Result<()> {
+    let path = format!("/sys/bus/usb/drivers/usb/{}", action);
+    let mut f = fs::OpenOptions::new().write(true).open(&path)?;
+    f.write_all(name.as_bytes())?;
+    Ok(())
+}
+
+fn read_dec(path: &Path, name: &str) -> Option<u32> {
+    read_attr(path, name).and_then(|s| s.parse().ok())
+}
+
+/// Return the stable bus-topology identifier for `usb` (e.g. `2-1.4`), taken
+/// from the name of its node under `/sys/bus/usb/devices`.
+pub fn usb_path(usb: &UsbDevice) -> String {
+    device_dir(usb)
+        .and_then(|dir| {
+            dir.file_name().map(|n| n.to_string_lossy().into_owned())
+        })
+        .unwrap_or_default()
+}
+
+// The sysfs directory for `usb`, preferring the handle captured at
+// enumeration time so two identical probes resolve to their own distinct
+// nodes. Falls back to a content match only for `UsbDevice`s built without a
+// stored path.
+fn device_dir(usb: &UsbDevice) -> Option<PathBuf> {
+    if let Some(ref path) = usb.sysfs_path {
+        return Some(path.clone());
+    }
+    find_sysfs_dir(usb)
+}
+
+fn find_sysfs_dir(usb: &UsbDevice) -> Option<PathBuf> {
+    let entries = match fs::read_dir(USB_DEVICES) {
+        Ok(entries) => entries,
+        Err(_) => return None,
+    };
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(_) => continue,
+        };
+        if read_hex(&path, "idVendor") == Some(usb.vendor_id)
+            && read_hex(&path, "idProduct") == Some(usb.product_id)
+            && read_string(&path, "serial") == usb.serial_number
+        {
+            return Some(path);
+        }
+    }
+    None
+}
+
+fn read_attr(path: &Path, name: &str) -> Option<String> {
+    let mut f = match fs::File::open(path.join(name)) {
+        Ok(f) => f,
+        Err(_) => return None,
+    };
+    let mut s = String::new();
+    match f.read_to_string(&mut s) {
+        Ok(_) => Some(s.trim().to_owned()),
+        Err(_) => None,
+    }
+}
+
+fn read_string(path: &Path, name: &str) -> String {
+    read_attr(path, name).unwrap_or_default()
+}
+
+fn read_hex(path: &Path, name: &str) -> Option<u16> {
+    read_attr(path, name).and_then(|s| u16::from_str_radix(&s, 16).ok())
+}
+
+// Interface attributes such as `bInterfaceNumber` are exposed as two hex
+// digits (e.g. `02`).
+fn read_hex8(path: &Path, name: &str) -> Option<u8> {
+    read_attr(path, name).and_then(|s| u8::from_str_radix(&s, 16).ok())
+}
@@ -0,0 +1,9 @@
+use device::UsbDevice;
+use ioreg;
+use Result;
+
+/// macOS backend. Delegates to the `ioreg` profiler, which parses the output
+/// of `ioreg -p IOUSB -l` into `UsbDevice` records.
+pub fn enumerate() -> Result<Vec<UsbDevice>> {
+    ioreg::enumerate()
+}
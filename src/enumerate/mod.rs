@@ -0,0 +1,31 @@
+use device::UsbDevice;
+use Result;
+
+// Platform-specific enumeration backends. Each backend exposes a single
+// `enumerate() -> Result<Vec<UsbDevice>>` and is selected at compile time.
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+use self::macos as backend;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "linux")]
+use self::linux as backend;
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+mod fallback;
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+use self::fallback as backend;
+
+/// Enumerate every USB device the active platform backend can see.
+pub fn enumerate() -> Result<Vec<UsbDevice>> {
+    backend::enumerate()
+}
+
+// Backends may resolve the serial and mass-storage paths for a device from
+// the platform's device tree. On macOS these are synthesized from templates
+// by the `Device` impls themselves, so only the Linux backend re-exports them.
+#[cfg(target_os = "linux")]
+pub use self::linux::{serial_path, msd_path, usb_path, reset_usb};
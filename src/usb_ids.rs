@@ -0,0 +1,16 @@
+use phf;
+
+// Maps generated from `usb.ids` by build.rs: `VENDORS` (vendor id) and
+// `PRODUCTS` (`(vendor << 16) | product`).
+include!(concat!(env!("OUT_DIR"), "/usb_ids_generated.rs"));
+
+/// Human-readable vendor name for `vendor_id`, if listed in `usb.ids`.
+pub fn vendor(vendor_id: u16) -> Option<&'static str> {
+    VENDORS.get(&vendor_id).cloned()
+}
+
+/// Human-readable product name for `(vendor_id, product_id)`, if listed.
+pub fn product(vendor_id: u16, product_id: u16) -> Option<&'static str> {
+    let key = ((vendor_id as u32) << 16) | (product_id as u32);
+    PRODUCTS.get(&key).cloned()
+}
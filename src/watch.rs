@@ -0,0 +1,142 @@
+use device::{search, Device, DeviceFilter};
+use std::collections::{HashSet, VecDeque};
+use Result;
+
+/// A hotplug event for a probe that matches the active `DeviceFilter`.
+pub enum DeviceEvent {
+    /// A matching device enumerated. Carries the resolved `Device`.
+    Arrived(Box<Device>),
+    /// A previously-seen matching device was removed. Carries its `hash()`.
+    Departed(String),
+}
+
+/// Block-and-iterate over hotplug events for probes matching `filter`.
+///
+/// The returned iterator yields `Arrived` for every device already present
+/// when watching starts and for each one that enumerates afterwards, and
+/// `Departed` when a previously-seen device is unplugged. It never ends on
+/// its own, so callers typically take the first event they care about (e.g.
+/// `bobbin watch --device 1a2b` exits as soon as that board arrives).
+///
+/// On Linux the wake-up is driven by a udev `MonitorBuilder` on the `usb`
+/// subsystem; on other platforms it falls back to polling `enumerate()` and
+/// diffing by `hash()`.
+pub fn watch<'a>(filter: &'a DeviceFilter) -> Result<impl Iterator<Item = DeviceEvent> + 'a> {
+    Watcher::new(filter)
+}
+
+struct Watcher<'a> {
+    filter: &'a DeviceFilter,
+    seen: HashSet<String>,
+    pending: VecDeque<DeviceEvent>,
+    source: Source,
+}
+
+impl<'a> Watcher<'a> {
+    fn new(filter: &'a DeviceFilter) -> Result<Watcher<'a>> {
+        Ok(Watcher {
+            filter: filter,
+            seen: HashSet::new(),
+            pending: VecDeque::new(),
+            source: Source::new()?,
+        })
+    }
+
+    // Re-enumerate and queue the delta against the set seen so far. Device
+    // construction always goes through `search()` so that `Departed` hashes
+    // line up with the hashes reported by `list`.
+    fn refresh(&mut self) {
+        let current = match search(self.filter) {
+            Ok(current) => current,
+            Err(_) => return,
+        };
+        let mut live = HashSet::new();
+        for device in current {
+            let hash = device.hash();
+            live.insert(hash.clone());
+            if self.seen.insert(hash) {
+                self.pending.push_back(DeviceEvent::Arrived(device));
+            }
+        }
+        let departed: Vec<String> =
+            self.seen.difference(&live).cloned().collect();
+        for hash in departed {
+            self.seen.remove(&hash);
+            self.pending.push_back(DeviceEvent::Departed(hash));
+        }
+    }
+}
+
+impl<'a> Iterator for Watcher<'a> {
+    type Item = DeviceEvent;
+
+    fn next(&mut self) -> Option<DeviceEvent> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            self.refresh();
+            if self.pending.is_empty() {
+                // Nothing changed; block until the platform signals a hotplug.
+                self.source.wait();
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod source {
+    use std::thread;
+    use std::time::Duration;
+    use udev::MonitorBuilder;
+    use Result;
+
+    /// udev monitor on the `usb` subsystem. Each received event is treated as
+    /// a hint to re-scan; the scan itself produces the authoritative state.
+    pub struct Source {
+        socket: ::udev::MonitorSocket,
+    }
+
+    impl Source {
+        pub fn new() -> Result<Source> {
+            let socket = MonitorBuilder::new()?
+                .match_subsystem("usb")?
+                .listen()?;
+            Ok(Source { socket: socket })
+        }
+
+        pub fn wait(&mut self) {
+            loop {
+                if self.socket.iter().next().is_some() {
+                    // Drain any coalesced events so one refresh covers them.
+                    while self.socket.iter().next().is_some() {}
+                    return;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod source {
+    use std::thread;
+    use std::time::Duration;
+    use Result;
+
+    /// Portable fallback: wake on a fixed poll interval and let the caller
+    /// diff the enumeration.
+    pub struct Source;
+
+    impl Source {
+        pub fn new() -> Result<Source> {
+            Ok(Source)
+        }
+
+        pub fn wait(&mut self) {
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+}
+
+use self::source::Source;